@@ -0,0 +1,162 @@
+use std::path::Path;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use tokio::process::Command;
+use zbus::Connection;
+
+use crate::config::Method;
+
+/// Something capable of actually applying a wallpaper image to the desktop.
+#[async_trait]
+pub trait Backend: Send + Sync {
+    async fn set_wallpaper(&self, path: &Path) -> anyhow::Result<()>;
+}
+
+/// Builds the `Backend` selected by `method`, performing whatever setup
+/// (e.g. opening a D-Bus connection) it needs.
+pub async fn build_backend(method: Method) -> anyhow::Result<Box<dyn Backend>> {
+    Ok(match method {
+        Method::Kde => Box::new(KdeBackend::new().await?),
+        Method::Gnome => Box::new(GnomeBackend),
+        Method::Sway => Box::new(SwayBackend),
+        Method::Hyprland => Box::new(HyprlandBackend),
+        Method::Command { command } => Box::new(CommandBackend::new(command)),
+    })
+}
+
+/// KDE Plasma, via a `PlasmaShell` D-Bus `evaluateScript` call.
+pub struct KdeBackend {
+    proxy: PlasmaShellProxy,
+}
+
+impl KdeBackend {
+    async fn new() -> anyhow::Result<Self> {
+        let connection = Connection::session().await?;
+        let proxy = PlasmaShellProxy::new(&connection).await?;
+        Ok(Self { proxy })
+    }
+}
+
+#[async_trait]
+impl Backend for KdeBackend {
+    async fn set_wallpaper(&self, path: &Path) -> anyhow::Result<()> {
+        self.proxy.evaluate_script(&format!(
+            r#"
+                var allDesktops = desktops();
+                for (i = 0; i < allDesktops.length; i++) {{
+                    d = allDesktops[i];
+                    d.wallpaperPlugin = "org.kde.image";
+                    d.currentConfigGroup = Array(
+                        "Wallpaper",
+                        "org.kde.image",
+                        "General"
+                    );
+                    d.writeConfig("Image", "file://{}");
+                }}
+            "#,
+            path.to_string_lossy(),
+        )).await?;
+        Ok(())
+    }
+}
+
+/// GNOME, via `gsettings set org.gnome.desktop.background picture-uri[-dark]`.
+pub struct GnomeBackend;
+
+#[async_trait]
+impl Backend for GnomeBackend {
+    async fn set_wallpaper(&self, path: &Path) -> anyhow::Result<()> {
+        let uri = format!("file://{}", path.to_string_lossy());
+        for key in ["picture-uri", "picture-uri-dark"] {
+            let status = Command::new("gsettings")
+                .args(["set", "org.gnome.desktop.background", key, &uri])
+                .status()
+                .await
+                .context("failed to run gsettings")?;
+            anyhow::ensure!(status.success(), "gsettings set {key} exited with {status}");
+        }
+        Ok(())
+    }
+}
+
+/// wlroots/sway, via `swaymsg output * bg <path> fill`.
+pub struct SwayBackend;
+
+#[async_trait]
+impl Backend for SwayBackend {
+    async fn set_wallpaper(&self, path: &Path) -> anyhow::Result<()> {
+        let status = Command::new("swaymsg")
+            .args(["output", "*", "bg", &path.to_string_lossy(), "fill"])
+            .status()
+            .await
+            .context("failed to run swaymsg")?;
+        anyhow::ensure!(status.success(), "swaymsg exited with {status}");
+        Ok(())
+    }
+}
+
+/// Hyprland, via `hyprctl hyprpaper` (preload then set).
+pub struct HyprlandBackend;
+
+#[async_trait]
+impl Backend for HyprlandBackend {
+    async fn set_wallpaper(&self, path: &Path) -> anyhow::Result<()> {
+        let path = path.to_string_lossy();
+
+        let preload_status = Command::new("hyprctl")
+            .args(["hyprpaper", "preload", &path])
+            .status()
+            .await
+            .context("failed to run hyprctl hyprpaper preload")?;
+        anyhow::ensure!(preload_status.success(), "hyprctl hyprpaper preload exited with {preload_status}");
+
+        let wallpaper_arg = format!(",{path}");
+        let set_status = Command::new("hyprctl")
+            .args(["hyprpaper", "wallpaper", &wallpaper_arg])
+            .status()
+            .await
+            .context("failed to run hyprctl hyprpaper wallpaper")?;
+        anyhow::ensure!(set_status.success(), "hyprctl hyprpaper wallpaper exited with {set_status}");
+        Ok(())
+    }
+}
+
+/// A user-supplied shell command template with a `{path}` placeholder,
+/// e.g. `feh --bg-fill {path}`.
+pub struct CommandBackend {
+    template: String,
+}
+
+impl CommandBackend {
+    fn new(template: String) -> Self {
+        Self { template }
+    }
+}
+
+#[async_trait]
+impl Backend for CommandBackend {
+    async fn set_wallpaper(&self, path: &Path) -> anyhow::Result<()> {
+        let command = self.template.replace("{path}", &path.to_string_lossy());
+        let status = Command::new("sh")
+            .args(["-c", &command])
+            .status()
+            .await
+            .context("failed to run wallpaper command")?;
+        anyhow::ensure!(status.success(), "wallpaper command exited with {status}");
+        Ok(())
+    }
+}
+
+#[zbus::proxy(
+    interface = "org.kde.PlasmaShell",
+    default_service = "org.kde.plasmashell",
+    default_path = "/PlasmaShell",
+)]
+trait PlasmaShell {
+    #[zbus(name = "evaluateScript")]
+    fn evaluate_script(
+        &self,
+        script: &str,
+    ) -> zbus::Result<String>;
+}
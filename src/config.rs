@@ -8,28 +8,101 @@ use crate::Light;
 pub struct Config {
     pub location: Location,
     pub method: Method,
+    /// Correct geometric elevation to apparent elevation (atmospheric
+    /// refraction) before matching it against wallpaper/twilight bounds.
+    #[serde(default)]
+    pub refraction: bool,
+    /// Time scale the input timestamp is treated as being in before it's
+    /// handed to the solar position calculation.
+    #[serde(default)]
+    pub time_scale: TimeScale,
+    /// Additional ΔT (TT − UT1) in seconds, on top of the fixed TAI − UTC
+    /// leap-second offset, applied when `time_scale` is `Tai`.
+    #[serde(default)]
+    pub delta_t_seconds: Option<f64>,
+    /// When set, blend between consecutive elevation-bounded wallpapers
+    /// over a band around their shared boundary instead of hard-switching.
+    #[serde(default)]
+    pub crossfade: Option<Crossfade>,
+    /// In `--daemon` mode, the longest the daemon will ever sleep in one
+    /// go before re-evaluating, in minutes. Defaults to 6 hours so polar
+    /// day/night (where no change is ever found) still gets revisited.
+    #[serde(default = "default_max_sleep_minutes")]
+    pub max_sleep_minutes: u64,
     #[serde(rename = "wallpaper")]
     pub wallpapers: Vec<Wallpaper>,
 }
 
+fn default_max_sleep_minutes() -> u64 {
+    6 * 60
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub struct Crossfade {
+    /// Width, in degrees of solar elevation, of the band approaching a
+    /// wallpaper boundary over which the outgoing wallpaper is blended
+    /// into the incoming one, reaching it fully exactly at the boundary.
+    pub band_degrees: f64,
+}
+
 #[derive(Deserialize)]
 pub struct Location {
     pub latitude: f64,
     pub longitude: f64,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeScale {
+    #[default]
+    Utc,
+    Tai,
+}
+
+#[derive(Deserialize, Clone)]
 #[serde(tag = "name", rename_all = "snake_case")]
 pub enum Method {
     Kde,
+    Gnome,
+    Sway,
+    Hyprland,
+    Command {
+        command: String,
+    },
 }
 
 #[derive(Deserialize)]
 pub struct Wallpaper {
     pub during: During,
+    /// Additional requirement on the Moon's altitude/illumination,
+    /// evaluated alongside `during`. Absent or empty bounds match anything.
+    #[serde(default)]
+    pub moon: Option<Moon>,
     pub path: PathBuf,
 }
 
+#[derive(Deserialize, Clone, Default)]
+pub struct Moon {
+    #[serde(default)]
+    pub altitude: Vec<Bound>,
+    #[serde(default)]
+    pub illumination: Vec<Bound>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub struct Bound {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Bound {
+    /// Inclusive on both ends, so e.g. `{ min = 0.9, max = 1.0 }` does
+    /// match a fully-illuminated moon (`k == 1.0`).
+    pub fn contains(&self, value: f64) -> bool {
+        (self.min..=self.max).contains(&value)
+    }
+}
+
 pub enum During {
     Lights(Vec<Light>),
     Elevation {
@@ -0,0 +1,76 @@
+use std::path::{Path, PathBuf};
+
+use image::{GenericImageView, RgbaImage};
+
+/// Drives crossfade blending across a single daemon run: reuses one temp
+/// file for its whole lifetime (the daemon is single-threaded, so writes
+/// to it are never concurrent) and caches the decoded source images
+/// across re-evaluations so only `t` needs to change between frames of
+/// the same transition.
+pub struct Crossfader {
+    temp_path: PathBuf,
+    decoded: Option<(PathBuf, PathBuf, RgbaImage, RgbaImage)>,
+}
+
+impl Crossfader {
+    pub fn new() -> Self {
+        Self {
+            temp_path: std::env::temp_dir().join(format!("time-of-day-wallpapers-crossfade-{}.png", std::process::id())),
+            decoded: None,
+        }
+    }
+
+    /// Blends `from` and `to` at progress `t` (0.0 = fully `from`, 1.0 =
+    /// fully `to`) and writes the result to this crossfader's temp file,
+    /// returning its path.
+    ///
+    /// Errors (and is meant to be treated as a no-op by the caller,
+    /// falling back to the un-blended wallpaper) if either image can't be
+    /// loaded or their dimensions don't match.
+    pub fn blend(&mut self, from: &Path, to: &Path, t: f64) -> anyhow::Result<&Path> {
+        let up_to_date = matches!(&self.decoded, Some((a, b, ..)) if a == from && b == to);
+        if !up_to_date {
+            let a = image::open(from)?.to_rgba8();
+            let b = image::open(to)?.to_rgba8();
+            anyhow::ensure!(a.dimensions() == b.dimensions(), "crossfade images have mismatched dimensions");
+            self.decoded = Some((from.to_path_buf(), to.to_path_buf(), a, b));
+        }
+        let (_, _, a, b) = self.decoded.as_ref().expect("just populated above");
+
+        let t = t.clamp(0.0, 1.0) as f32;
+        let mut out = RgbaImage::new(a.width(), a.height());
+        for (x, y, out_px) in out.enumerate_pixels_mut() {
+            let pa = a.get_pixel(x, y);
+            let pb = b.get_pixel(x, y);
+            *out_px = image::Rgba([
+                blend_channel(pa[0], pb[0], t),
+                blend_channel(pa[1], pb[1], t),
+                blend_channel(pa[2], pb[2], t),
+                blend_channel(pa[3], pb[3], t),
+            ]);
+        }
+
+        out.save(&self.temp_path)?;
+        restrict_permissions(&self.temp_path)?;
+        Ok(&self.temp_path)
+    }
+}
+
+fn blend_channel(a: u8, b: u8, t: f32) -> u8 {
+    (a as f32 * (1.0 - t) + b as f32 * t).round() as u8
+}
+
+/// Restricts the blended image to owner-only access, since `temp_dir()` is
+/// typically a world-writable shared directory.
+#[cfg(unix)]
+fn restrict_permissions(path: &Path) -> anyhow::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &Path) -> anyhow::Result<()> {
+    Ok(())
+}
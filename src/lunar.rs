@@ -0,0 +1,66 @@
+use chrono::{DateTime, Utc};
+
+/// Lunar altitude (degrees) and illuminated fraction (0..1) for an observer
+/// at `latitude`/`longitude`.
+///
+/// Computed from a low-precision lunar position series (Meeus's mean
+/// longitude plus its largest periodic terms) — good enough to tell a
+/// bright full moon from a dark new moon, not eclipse-grade.
+pub struct MoonState {
+    pub altitude: f64,
+    pub illuminated_fraction: f64,
+}
+
+const OBLIQUITY_DEG: f64 = 23.439;
+
+pub fn moon_state(now: DateTime<Utc>, latitude: f64, longitude: f64) -> MoonState {
+    let t = centuries_since_j2000(now);
+
+    let l_prime = norm_deg(218.3164477 + 481267.88123421 * t);
+    let d_moon = norm_deg(297.8501921 + 445267.1114034 * t);
+    let m_sun = norm_deg(357.5291092 + 35999.0502909 * t);
+    let m_moon = norm_deg(134.9633964 + 477198.8675055 * t);
+    let f = norm_deg(93.2720950 + 483202.0175233 * t);
+
+    let ecliptic_longitude = l_prime
+        + 6.288774 * m_moon.to_radians().sin()
+        + 1.274027 * (2.0 * d_moon - m_moon).to_radians().sin()
+        + 0.658314 * (2.0 * d_moon).to_radians().sin()
+        + 0.213618 * (2.0 * m_moon).to_radians().sin();
+    let ecliptic_latitude = 5.128122 * f.to_radians().sin()
+        + 0.280602 * (m_moon + f).to_radians().sin();
+
+    let eps = OBLIQUITY_DEG.to_radians();
+    let lambda = ecliptic_longitude.to_radians();
+    let beta = ecliptic_latitude.to_radians();
+    let right_ascension = norm_deg((lambda.sin() * eps.cos() - beta.tan() * eps.sin()).atan2(lambda.cos()).to_degrees());
+    let declination = (beta.sin() * eps.cos() + beta.cos() * eps.sin() * lambda.sin()).asin();
+
+    let local_sidereal_time = norm_deg(280.46061837 + 360.98564736629 * (t * 36525.0) + longitude);
+    let hour_angle = norm_deg(local_sidereal_time - right_ascension).to_radians();
+    let lat = latitude.to_radians();
+    let altitude = (lat.sin() * declination.sin() + lat.cos() * declination.cos() * hour_angle.cos())
+        .asin()
+        .to_degrees();
+
+    // Sun's ecliptic longitude, from its mean longitude plus the equation
+    // of center, to get the Sun-Moon elongation for the phase angle.
+    let sun_mean_longitude = norm_deg(280.4665 + 36000.7698 * t);
+    let sun_ecliptic_longitude = norm_deg(sun_mean_longitude + 1.914602 * m_sun.to_radians().sin());
+    let elongation = (ecliptic_longitude - sun_ecliptic_longitude).to_radians();
+    let phase_angle_cosine = -elongation.cos();
+    let illuminated_fraction = (1.0 + phase_angle_cosine) / 2.0;
+
+    MoonState { altitude, illuminated_fraction }
+}
+
+fn centuries_since_j2000(now: DateTime<Utc>) -> f64 {
+    const J2000_UNIX_SECONDS: i64 = 946_728_000; // 2000-01-01T12:00:00Z
+    let seconds = now.timestamp() - J2000_UNIX_SECONDS;
+    seconds as f64 / 86_400.0 / 36525.0
+}
+
+fn norm_deg(deg: f64) -> f64 {
+    let r = deg % 360.0;
+    if r < 0.0 { r + 360.0 } else { r }
+}
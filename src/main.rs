@@ -1,72 +1,254 @@
-use std::ops::Range;
+use std::{ops::Range, time::Duration as StdDuration};
 
 use anyhow::Context;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
-use spa::{SolarPos, StdFloatOps};
-use zbus::Connection;
+use spa::StdFloatOps;
 
-use crate::config::{Config, During};
+use crate::config::{Config, During, TimeScale};
 
+mod backend;
 mod config;
+mod crossfade;
+mod lunar;
+
+/// TAI − UTC under the current leap-second table.
+const TAI_MINUS_UTC: chrono::Duration = chrono::Duration::seconds(37);
+
+/// Coarse step used when scanning forward for the next wallpaper change.
+const SCAN_STEP: chrono::Duration = chrono::Duration::minutes(1);
+
+/// How precisely the crossing time is pinned down once a coarse step has
+/// bracketed it.
+const BISECT_PRECISION: chrono::Duration = chrono::Duration::seconds(1);
+
+/// How often the daemon re-applies the wallpaper while a crossfade is in
+/// progress, so the blend visibly animates instead of jumping straight
+/// from the first blended frame to the final hard crossing.
+const CROSSFADE_REEVAL_STEP: chrono::Duration = chrono::Duration::seconds(30);
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let config_path = std::env::args().nth(1)
+    let args: Vec<String> = std::env::args().collect();
+    let daemon = args.iter().any(|a| a == "--daemon");
+    let config_path = args.iter().skip(1)
+        .find(|a| a.as_str() != "--daemon")
         .context("missing config path as first arg")?;
-    let config = tokio::fs::read_to_string(&config_path).await
+    let config = tokio::fs::read_to_string(config_path).await
         .context("could not read config")?;
     let config: Config = toml::from_str(&config)
         .context("could not deserialise config")?;
 
-    let now = Utc::now();
-    let pos = spa::solar_position::<StdFloatOps>(now, config.location.latitude, config.location.longitude)
+    let backend = backend::build_backend(config.method.clone()).await?;
+    let mut crossfader = crossfade::Crossfader::new();
+
+    loop {
+        let now = Utc::now();
+        let index = select_wallpaper(&config, now)?;
+        let path = wallpaper_path(&config, now, index, &mut crossfader);
+        backend.set_wallpaper(&path).await?;
+
+        if !daemon {
+            break;
+        }
+
+        let mut sleep_until = next_change(&config, now, index);
+        if crossfade_progress(&config, now, index).is_some() {
+            sleep_until = sleep_until.min(now + CROSSFADE_REEVAL_STEP);
+        }
+        let duration = (sleep_until - Utc::now()).max(chrono::Duration::zero());
+        tokio::time::sleep(duration.to_std().unwrap_or(StdDuration::ZERO)).await;
+    }
+
+    Ok(())
+}
+
+/// The Sun's elevation and azimuth at a given time, plus the derived
+/// `Light` phase and the Moon's altitude/illumination, as used for
+/// wallpaper selection.
+#[derive(Clone, Copy)]
+struct SolarState {
+    elevation: f64,
+    azimuth: f64,
+    light: Light,
+    moon_altitude: f64,
+    moon_illumination: f64,
+}
+
+/// Computes the `SolarState` used for wallpaper selection at `now`,
+/// applying `config`'s time scale and refraction settings.
+fn solar_state(config: &Config, now: DateTime<Utc>) -> anyhow::Result<SolarState> {
+    let solar_time = solar_input_time(config, now);
+    let pos = spa::solar_position::<StdFloatOps>(solar_time, config.location.latitude, config.location.longitude)
         .context("could not determine solar position")?;
-    let elevation = 90_f64 - pos.zenith_angle;
-    let light = Light::from(pos.clone());
-    // let declination = calculate_declination(now);
-    // // solar noon is when zenith angle is equal to latitude - solar declination angle
-    // println!("light: {light:?}");
-    // println!("declination: {declination:?}");
-    // println!("azimuth: {}", pos.azimuth);
-    // println!("zenith angle: {}", pos.zenith_angle);
-    // println!("altitude: {altitude}");
-    // println!("solar noon: {}", config.location.latitude - declination.unwrap_or_default());
-
-    let wallpaper = config.wallpapers.iter()
-        .find(|wp| match &wp.during {
-            During::Lights(lights) => lights.iter().any(|&l| l == light),
-            During::Elevation { rising, setting } => (pos.azimuth < 180.0 && rising.iter().any(|range| range.contains(&elevation)))
-                || (pos.azimuth > 180.0 && setting.iter().any(|range| range.contains(&elevation))),
+    let geometric_elevation = 90_f64 - pos.zenith_angle;
+    let elevation = if config.refraction {
+        apparent_elevation(geometric_elevation)
+    } else {
+        geometric_elevation
+    };
+    let light = Light::from_elevation_azimuth(elevation, pos.azimuth);
+    let moon = lunar::moon_state(solar_time, config.location.latitude, config.location.longitude);
+    Ok(SolarState {
+        elevation,
+        azimuth: pos.azimuth,
+        light,
+        moon_altitude: moon.altitude,
+        moon_illumination: moon.illuminated_fraction,
+    })
+}
+
+/// Whether `moon` (a wallpaper's optional Moon requirement) is satisfied by
+/// `state`. A requirement with no bounds set, or no requirement at all,
+/// always matches.
+fn matches_moon(moon: &Option<config::Moon>, state: &SolarState) -> bool {
+    let Some(moon) = moon else { return true };
+    (moon.altitude.is_empty() || moon.altitude.iter().any(|b| b.contains(state.moon_altitude)))
+        && (moon.illumination.is_empty() || moon.illumination.iter().any(|b| b.contains(state.moon_illumination)))
+}
+
+/// Picks the index into `config.wallpapers` of the wallpaper that matches
+/// `state`, falling back to the `During::Any` wallpaper if none do.
+fn pick_wallpaper_index(config: &Config, state: &SolarState) -> Option<usize> {
+    config.wallpapers.iter()
+        .position(|wp| matches_moon(&wp.moon, state) && match &wp.during {
+            During::Lights(lights) => lights.iter().any(|&l| l == state.light),
+            During::Elevation { rising, setting } => (state.azimuth < 180.0 && rising.iter().any(|range| range.contains(&state.elevation)))
+                || (state.azimuth > 180.0 && setting.iter().any(|range| range.contains(&state.elevation))),
             During::LightsAndElevation { lights, rising, setting } => {
-                lights.iter().any(|&l| l == light)
-                || (pos.azimuth < 180.0 && rising.iter().any(|range| range.contains(&elevation)))
-                || (pos.azimuth > 180.0 && setting.iter().any(|range| range.contains(&elevation)))
+                lights.iter().any(|&l| l == state.light)
+                || (state.azimuth < 180.0 && rising.iter().any(|range| range.contains(&state.elevation)))
+                || (state.azimuth > 180.0 && setting.iter().any(|range| range.contains(&state.elevation)))
             }
             During::Any => false,
         })
-        .or_else(|| config.wallpapers.iter().find(|wp| wp.during.is_any()))
-        .context("no configured wallpaper")?;
-
-    let connection = Connection::session().await?;
-    let proxy = PlasmaShellProxy::new(&connection).await?;
-    proxy.evaluate_script(&format!(
-        r#"
-            var allDesktops = desktops();
-            for (i = 0; i < allDesktops.length; i++) {{
-                d = allDesktops[i];
-                d.wallpaperPlugin = "org.kde.image";
-                d.currentConfigGroup = Array(
-                    "Wallpaper",
-                    "org.kde.image",
-                    "General"
-                );
-                d.writeConfig("Image", "file://{}");
-            }}
-        "#,
-        wallpaper.path.to_string_lossy(),
-    )).await?;
-    Ok(())
+        .or_else(|| config.wallpapers.iter().position(|wp| wp.during.is_any() && matches_moon(&wp.moon, state)))
+}
+
+/// Picks the index into `config.wallpapers` of the wallpaper that should be
+/// showing at `now`.
+fn select_wallpaper(config: &Config, now: DateTime<Utc>) -> anyhow::Result<usize> {
+    let state = solar_state(config, now)?;
+    pick_wallpaper_index(config, &state).context("no configured wallpaper")
+}
+
+/// The elevation ranges (rising or setting, matching `rising`) that a
+/// wallpaper's `During` is bounded by, if it has any.
+fn elevation_ranges(during: &During, rising: bool) -> Option<&[Range<f64>]> {
+    match during {
+        During::Elevation { rising: r, setting: s } | During::LightsAndElevation { rising: r, setting: s, .. } => {
+            Some(if rising { r } else { s })
+        }
+        _ => None,
+    }
+}
+
+/// If `config.crossfade` is set and `now` falls within its band around the
+/// boundary of `current_index`'s elevation range, returns the outgoing and
+/// incoming wallpaper indices and how far through the band `now` is (0.0 at
+/// the band's start, 1.0 once the incoming wallpaper fully takes over).
+fn crossfade_progress(config: &Config, now: DateTime<Utc>, current_index: usize) -> Option<(usize, usize, f64)> {
+    let crossfade = config.crossfade?;
+    let state = solar_state(config, now).ok()?;
+    let rising = state.azimuth < 180.0;
+    let range = elevation_ranges(&config.wallpapers[current_index].during, rising)?
+        .iter()
+        .find(|range| range.contains(&state.elevation))?;
+
+    let (near_edge, direction) = if rising {
+        (range.end, 1.0)
+    } else {
+        (range.start, -1.0)
+    };
+    let distance = (near_edge - state.elevation).abs();
+    if distance > crossfade.band_degrees {
+        return None;
+    }
+
+    let probe = SolarState { elevation: near_edge + direction * 0.001, ..state };
+    let next_index = pick_wallpaper_index(config, &probe)?;
+    if next_index == current_index {
+        return None;
+    }
+
+    let t = 1.0 - distance / crossfade.band_degrees;
+    Some((current_index, next_index, t))
+}
+
+/// The path to hand to the backend for the wallpaper selected at `now`:
+/// the plain wallpaper path, or a temporary blended image if `config`'s
+/// crossfade band applies and the blend succeeds.
+fn wallpaper_path(config: &Config, now: DateTime<Utc>, index: usize, crossfader: &mut crossfade::Crossfader) -> std::path::PathBuf {
+    if let Some((from, to, t)) = crossfade_progress(config, now, index) {
+        if let Ok(blended) = crossfader.blend(&config.wallpapers[from].path, &config.wallpapers[to].path, t) {
+            return blended.to_path_buf();
+        }
+    }
+
+    config.wallpapers[index].path.clone()
+}
+
+/// Converts `now` into the time scale `config.time_scale` expects before
+/// it's handed to `spa::solar_position`.
+fn solar_input_time(config: &Config, now: DateTime<Utc>) -> DateTime<Utc> {
+    match config.time_scale {
+        TimeScale::Utc => now,
+        TimeScale::Tai => {
+            let delta_t_millis = (config.delta_t_seconds.unwrap_or(0.0) * 1000.0).round() as i64;
+            now + TAI_MINUS_UTC + chrono::Duration::milliseconds(delta_t_millis)
+        }
+    }
+}
+
+/// Corrects geometric elevation `h` (degrees) to apparent elevation using
+/// Bennett's atmospheric refraction formula, clamping the input to avoid
+/// the formula blowing up near/below the horizon.
+fn apparent_elevation(h: f64) -> f64 {
+    let clamped = h.max(-1.0);
+    let r_arcmin = 1.0 / (clamped + 7.31 / (clamped + 4.4)).to_radians().tan();
+    h + r_arcmin / 60.0
+}
+
+/// Finds the next moment at which `select_wallpaper` would stop returning
+/// `current_index`.
+///
+/// Scans forward from `now` in `SCAN_STEP` increments until the selection
+/// differs, then bisects between the last matching and first non-matching
+/// timestamp down to `BISECT_PRECISION` to pin the crossing. If no change
+/// turns up within `config.max_sleep_minutes` (polar day/night), gives up
+/// and returns `now` plus that many minutes so the daemon re-evaluates
+/// periodically instead of sleeping forever.
+fn next_change(config: &Config, now: DateTime<Utc>, current_index: usize) -> DateTime<Utc> {
+    let deadline = now + chrono::Duration::minutes(config.max_sleep_minutes as i64);
+    let same_as_current = |t: DateTime<Utc>| select_wallpaper(config, t).unwrap_or(usize::MAX) == current_index;
+
+    let mut last_same = now;
+    let mut t = now + SCAN_STEP;
+    let first_different = loop {
+        if t >= deadline {
+            return deadline;
+        }
+
+        if same_as_current(t) {
+            last_same = t;
+            t += SCAN_STEP;
+        } else {
+            break t;
+        }
+    };
+
+    let mut lo = last_same;
+    let mut hi = first_different;
+    while hi - lo > BISECT_PRECISION {
+        let mid = lo + (hi - lo) / 2;
+        if same_as_current(mid) {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+
+    hi
 }
 
 // /// Calculates the Sun's declination in degrees.
@@ -166,28 +348,14 @@ enum Light {
     Night,
 }
 
-impl From<SolarPos> for Light {
-    fn from(pos: SolarPos) -> Self {
-        let elevation = 90_f64 - pos.zenith_angle;
+impl Light {
+    fn from_elevation_azimuth(elevation: f64, azimuth: f64) -> Self {
         for light in LightGeneric::ALL {
             if light.altitude_bounds().iter().any(|range| range.contains(&elevation)) {
-                return light.to_specific(pos.azimuth);
+                return light.to_specific(azimuth);
             }
         }
 
         unreachable!("{elevation}")
     }
 }
-
-#[zbus::proxy(
-    interface = "org.kde.PlasmaShell",
-    default_service = "org.kde.plasmashell",
-    default_path = "/PlasmaShell",
-)]
-trait PlasmaShell {
-    #[zbus(name = "evaluateScript")]
-    fn evaluate_script(
-        &self,
-        script: &str,
-    ) -> zbus::Result<String>;
-}